@@ -0,0 +1,148 @@
+#![cfg(feature = "cli")]
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::fs::File;
+use std::path::Path;
+
+use config::{self, Config, ConfigUser};
+use db::DB;
+use errors::*;
+use vfs::{MountPoint, StorageBackendKind};
+
+// Headless config administration, for first-run setup and scripted provisioning where editing
+// the TOML file or the database by hand isn't practical.
+pub fn run(db: &DB) -> Result<()> {
+	let matches = App::new("polaris")
+		.subcommand(SubCommand::with_name("init").about("Create a fresh database"))
+		.subcommand(SubCommand::with_name("import")
+			.about("Load a TOML or JSON config file into the database")
+			.arg(Arg::with_name("file").required(true)))
+		.subcommand(SubCommand::with_name("export")
+			.about("Write the database config out to a TOML or JSON file")
+			.arg(Arg::with_name("file").required(true)))
+		.subcommand(SubCommand::with_name("user")
+			.subcommand(SubCommand::with_name("add")
+				.arg(Arg::with_name("name").required(true))
+				.arg(Arg::with_name("password").required(true))
+				.arg(Arg::with_name("admin").long("admin")))
+			.subcommand(SubCommand::with_name("remove").arg(Arg::with_name("name").required(true)))
+			.subcommand(SubCommand::with_name("passwd")
+				.arg(Arg::with_name("name").required(true))
+				.arg(Arg::with_name("password").required(true))))
+		.subcommand(SubCommand::with_name("mount")
+			.subcommand(SubCommand::with_name("add")
+				.arg(Arg::with_name("name").required(true))
+				.arg(Arg::with_name("source").required(true))
+				.arg(Arg::with_name("remote-url").long("remote-url").takes_value(true)))
+			.subcommand(SubCommand::with_name("remove").arg(Arg::with_name("name").required(true))))
+		.get_matches();
+
+	match matches.subcommand() {
+		("init", _) => init(db),
+		("import", Some(m)) => import(db, Path::new(m.value_of("file").unwrap())),
+		("export", Some(m)) => export(db, Path::new(m.value_of("file").unwrap())),
+		("user", Some(m)) => user(db, m),
+		("mount", Some(m)) => mount(db, m),
+		_ => bail!("No subcommand given. Run with --help for usage."),
+	}
+}
+
+fn init(db: &DB) -> Result<()> {
+	config::overwrite(db, &empty_config())
+}
+
+fn import(db: &DB, file: &Path) -> Result<()> {
+	let new_config = match extension(file) {
+		"json" => config::parse_json(&read_to_string(file)?)?,
+		_ => config::parse_toml_file(file)?,
+	};
+	config::overwrite(db, &new_config)
+}
+
+fn export(db: &DB, file: &Path) -> Result<()> {
+	let current_config = config::read(db)?;
+	match extension(file) {
+		"json" => {
+			use std::io::Write;
+			write!(File::create(file)?, "{}", config::to_json(&current_config)?)?
+		}
+		_ => config::write_toml_file(file, &current_config)?,
+	}
+	Ok(())
+}
+
+fn user(db: &DB, matches: &ArgMatches) -> Result<()> {
+	let mut users = config::read(db)?.users.unwrap_or_else(Vec::new);
+	match matches.subcommand() {
+		("add", Some(m)) |
+		("passwd", Some(m)) => {
+			let name = m.value_of("name").unwrap();
+			let admin = users.iter().any(|u| u.name == name && u.admin) || m.is_present("admin");
+			users.retain(|u| u.name != name);
+			users.push(ConfigUser {
+			               name: name.to_owned(),
+			               password: m.value_of("password").unwrap().to_owned(),
+			               admin: admin,
+			           });
+		}
+		("remove", Some(m)) => {
+			let name = m.value_of("name").unwrap();
+			users.retain(|u| u.name != name);
+		}
+		_ => bail!("No user subcommand given. Run with --help for usage."),
+	}
+	let mut new_config = empty_config();
+	new_config.users = Some(users);
+	config::ammend(db, &new_config)
+}
+
+fn mount(db: &DB, matches: &ArgMatches) -> Result<()> {
+	let mut mount_dirs = config::read(db)?.mount_dirs.unwrap_or_else(Vec::new);
+	match matches.subcommand() {
+		("add", Some(m)) => {
+			let name = m.value_of("name").unwrap();
+			let remote_url = m.value_of("remote-url").map(|s| s.to_owned());
+			mount_dirs.retain(|m| m.name != name);
+			mount_dirs.push(MountPoint {
+			                     source: m.value_of("source").unwrap().to_owned(),
+			                     name: name.to_owned(),
+			                     backend: if remote_url.is_some() {
+				                     StorageBackendKind::Network
+				                    } else {
+				                     StorageBackendKind::Local
+				                    },
+			                     remote_url: remote_url,
+			                 });
+		}
+		("remove", Some(m)) => {
+			let name = m.value_of("name").unwrap();
+			mount_dirs.retain(|m| m.name != name);
+		}
+		_ => bail!("No mount subcommand given. Run with --help for usage."),
+	}
+	let mut new_config = empty_config();
+	new_config.mount_dirs = Some(mount_dirs);
+	config::ammend(db, &new_config)
+}
+
+fn empty_config() -> Config {
+	Config {
+		album_art_pattern: None,
+		reindex_every_n_seconds: None,
+		password_hash_iterations: None,
+		mount_dirs: None,
+		users: None,
+		ydns: None,
+	}
+}
+
+fn extension(path: &Path) -> &str {
+	path.extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+fn read_to_string(path: &Path) -> Result<String> {
+	use std::io::Read;
+	let mut content = String::new();
+	File::open(path)?.read_to_string(&mut content)?;
+	Ok(content)
+}
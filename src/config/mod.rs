@@ -0,0 +1,597 @@
+use diesel;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use regex::Regex;
+use serde_json;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path;
+use toml;
+
+use db::DB;
+use db::ConnectionSource;
+use db::{ddns_config, misc_settings, mount_points, users};
+use ddns::DDNSConfig;
+use errors::*;
+use user::*;
+use vfs::{self, MountPoint};
+
+pub mod watcher;
+
+#[derive(Debug, Queryable)]
+pub struct MiscSettings {
+	id: i32,
+	pub auth_secret: String,
+	pub index_sleep_duration_seconds: i32,
+	pub index_album_art_pattern: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "mount_points"]
+struct NewMountPoint<'a> {
+	source: &'a str,
+	name: &'a str,
+	backend: &'a str,
+	remote_url: Option<&'a str>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConfigUser {
+	pub name: String,
+	pub password: String,
+	#[serde(default)]
+	pub admin: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+	pub album_art_pattern: Option<String>,
+	pub reindex_every_n_seconds: Option<i32>,
+	// How many PBKDF2 iterations to use when hashing a password set or changed from now on.
+	// Existing users keep whatever iteration count they were hashed with (see `user::User`).
+	pub password_hash_iterations: Option<i32>,
+	pub mount_dirs: Option<Vec<MountPoint>>,
+	pub users: Option<Vec<ConfigUser>>,
+	pub ydns: Option<DDNSConfig>,
+}
+
+impl Config {
+	fn clean_paths(&mut self) -> Result<()> {
+		if let Some(ref mut mount_dirs) = self.mount_dirs {
+			for mount_dir in mount_dirs {
+				// Separator normalization only makes sense for paths on the local filesystem;
+				// a remote mount's `source` is an opaque identifier, not an OS path.
+				if mount_dir.backend != vfs::StorageBackendKind::Local {
+					continue;
+				}
+				match clean_path_string(&mount_dir.source).to_str() {
+					Some(p) => mount_dir.source = p.to_owned(),
+					_ => bail!("Bad mount directory path"),
+				}
+			}
+		}
+		Ok(())
+	}
+
+	// Catches config mistakes as early as possible, rather than at index time (a bad album art
+	// pattern) or silently (a duplicate mount name shadowing another one).
+	fn validate(&self) -> Result<()> {
+		if let Some(ref album_art_pattern) = self.album_art_pattern {
+			Regex::new(album_art_pattern)
+				.map_err(|e| format!("Invalid album art pattern `{}`: {}", album_art_pattern, e))?;
+		}
+
+		if let Some(hash_iterations) = self.password_hash_iterations {
+			// `User::new`/`hash_password` unwrap a `NonZeroU32` of this value, and a bad value
+			// persists to `misc_settings` where it then blows up (or, cast to u32, runs for
+			// billions of rounds) on every later, unrelated password change.
+			if hash_iterations <= 0 {
+				bail!("password_hash_iterations must be a positive number, got {}", hash_iterations);
+			}
+		}
+
+		if let Some(ref mount_dirs) = self.mount_dirs {
+			let mut seen_names = HashSet::new();
+			let mut seen_sources = HashSet::new();
+			for mount_dir in mount_dirs {
+				if mount_dir.name.is_empty() {
+					bail!("Mount points cannot have an empty name");
+				}
+				if mount_dir.source.is_empty() {
+					bail!("Mount point `{}` cannot have an empty source", mount_dir.name);
+				}
+				if !seen_names.insert(mount_dir.name.as_str()) {
+					bail!("Duplicate mount point name `{}`", mount_dir.name);
+				}
+				// `mount_points` is keyed on `source`, not `name` (see db.rs); a duplicate here
+				// would pass `ammend`'s delete-then-insert and blow up on the underlying
+				// primary key violation instead of failing with an actionable message.
+				if !seen_sources.insert(mount_dir.source.as_str()) {
+					bail!("Duplicate mount point source `{}`", mount_dir.source);
+				}
+				// Nothing resolves a non-`Local` backend into an actual reader yet (see
+				// `vfs::NetworkStorageBackend`), but a missing `remote_url` is still a config mistake worth
+				// catching now rather than leaving a mount that can never work.
+				if mount_dir.backend != vfs::StorageBackendKind::Local && mount_dir.remote_url.is_none() {
+					bail!("Mount point `{}` needs a remote_url for backend `{}`",
+					      mount_dir.name,
+					      mount_dir.backend);
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+pub fn parse_json(content: &str) -> Result<Config> {
+	let mut config = serde_json::from_str::<Config>(content)?;
+	config.clean_paths()?;
+	config.validate()?;
+	Ok(config)
+}
+
+pub fn parse_toml_file(path: &path::Path) -> Result<Config> {
+	println!("Config file path: {}", path.to_string_lossy());
+	let mut config_file = fs::File::open(path)?;
+	let mut config_file_content = String::new();
+	config_file.read_to_string(&mut config_file_content)?;
+	let mut config = toml::de::from_str::<Config>(&config_file_content)?;
+	config.clean_paths()?;
+	config.validate()?;
+	Ok(config)
+}
+
+pub fn write_toml_file(path: &path::Path, config: &Config) -> Result<()> {
+	use std::io::Write;
+	let content = toml::ser::to_string_pretty(&to_portable(config))?;
+	let mut config_file = fs::File::create(path)?;
+	config_file.write_all(content.as_bytes())?;
+	Ok(())
+}
+
+pub fn to_json(config: &Config) -> Result<String> {
+	Ok(serde_json::to_string_pretty(&to_portable(config))?)
+}
+
+// Undoes `clean_paths`'s OS-specific separator rewriting so a config written out on one OS reads
+// back correctly (via `parse_toml_file`/`parse_json`) on another.
+fn to_portable(config: &Config) -> Config {
+	let mut config = config.clone();
+	if let Some(ref mut mount_dirs) = config.mount_dirs {
+		for mount_dir in mount_dirs {
+			if mount_dir.backend == vfs::StorageBackendKind::Local {
+				mount_dir.source = portable_path_string(&mount_dir.source);
+			}
+		}
+	}
+	config
+}
+
+fn portable_path_string(path_string: &str) -> String {
+	path_string.replace(path::MAIN_SEPARATOR, "/")
+}
+
+pub fn read<T>(db: &T) -> Result<Config>
+	where T: ConnectionSource
+{
+	use self::misc_settings::dsl::*;
+	use self::mount_points::dsl::*;
+	use self::ddns_config::dsl::*;
+
+	let connection = db.get_connection()?;
+	let connection = &connection;
+
+	let mut config = Config {
+		album_art_pattern: None,
+		reindex_every_n_seconds: None,
+		password_hash_iterations: None,
+		mount_dirs: None,
+		users: None,
+		ydns: None,
+	};
+
+	let (art_pattern, sleep_duration, hash_iterations) = misc_settings
+		.select((index_album_art_pattern, index_sleep_duration_seconds, password_hash_iterations))
+		.get_result(connection)?;
+	config.album_art_pattern = Some(art_pattern);
+	config.reindex_every_n_seconds = Some(sleep_duration);
+	config.password_hash_iterations = Some(hash_iterations);
+
+	let mount_rows: Vec<(String, String, String, Option<String>)> = mount_points
+		.select((source, name, backend, remote_url))
+		.get_results(connection)?;
+	let mount_dirs = mount_rows
+		.into_iter()
+		.map(|(row_source, row_name, row_backend, row_remote_url)| {
+			Ok(MountPoint {
+			       source: row_source,
+			       name: row_name,
+			       backend: vfs::StorageBackendKind::parse(&row_backend)?,
+			       remote_url: row_remote_url,
+			   })
+		})
+		.collect::<Result<Vec<MountPoint>>>()?;
+	config.mount_dirs = Some(mount_dirs);
+
+	// Stored passwords are hashed and cannot be recovered, so exported users always come back
+	// with an empty password; `ammend` treats an empty password as "leave it alone" on import.
+	let user_rows: Vec<(String, bool)> = users::table
+		.select((users::columns::name, users::columns::admin))
+		.get_results(connection)?;
+	config.users = Some(user_rows
+	                        .into_iter()
+	                        .map(|(name, admin)| {
+		                             ConfigUser {
+		                                 name: name,
+		                                 password: "".to_owned(),
+		                                 admin: admin,
+		                             }
+		                            })
+	                        .collect::<_>());
+
+	let ydns = ddns_config
+		.select((host, username, password))
+		.get_result(connection)?;
+	config.ydns = Some(ydns);
+
+	Ok(config)
+}
+
+fn reset<T>(db: &T) -> Result<()>
+	where T: ConnectionSource
+{
+	let connection = db.get_connection()?;
+	let connection = &connection;
+
+	diesel::delete(mount_points::table).execute(connection)?;
+	diesel::delete(users::table).execute(connection)?;
+
+	Ok(())
+}
+
+pub fn overwrite<T>(db: &T, new_config: &Config) -> Result<()>
+	where T: ConnectionSource
+{
+	// Validate before `reset` wipes `mount_points`/`users` -- otherwise a bad `new_config` leaves
+	// the database empty instead of failing fast with the old config still in place.
+	new_config.validate()?;
+	reset(db)?;
+	ammend(db, new_config)
+}
+
+pub fn ammend<T>(db: &T, new_config: &Config) -> Result<()>
+	where T: ConnectionSource
+{
+	new_config.validate()?;
+	let connection = db.get_connection()?;
+	connection.transaction(|| ammend_connection(&connection, new_config))
+}
+
+// Applies `new_config` using an already-acquired connection. Callers are expected to run this
+// inside their own `connection.transaction` (as `ammend` and the config file watcher both do) so
+// that e.g. a failed user insert can't leave `mount_points` deleted but not reinserted.
+pub(crate) fn ammend_connection(connection: &SqliteConnection, new_config: &Config) -> Result<()> {
+	if let Some(hash_iterations) = new_config.password_hash_iterations {
+		diesel::update(misc_settings::table)
+			.set(misc_settings::password_hash_iterations.eq(hash_iterations))
+			.execute(connection)?;
+	}
+
+	if let Some(ref mount_dirs) = new_config.mount_dirs {
+		diesel::delete(mount_points::table).execute(connection)?;
+		let new_mount_dirs: Vec<NewMountPoint> = mount_dirs
+			.iter()
+			.map(|m| {
+				     NewMountPoint {
+				         source: &m.source,
+				         name: &m.name,
+				         backend: m.backend.as_str(),
+				         remote_url: m.remote_url.as_ref().map(|s| s.as_str()),
+				     }
+				    })
+			.collect();
+		diesel::insert(&new_mount_dirs)
+			.into(mount_points::table)
+			.execute(connection)?;
+	}
+
+	if let Some(ref config_users) = new_config.users {
+		let kept_names: Vec<&str> = config_users.iter().map(|u| u.name.as_str()).collect();
+		diesel::delete(users::table.filter(users::name.ne_all(&kept_names))).execute(connection)?;
+
+		let existing_names: Vec<String> = users::table
+			.select(users::columns::name)
+			.get_results(connection)?;
+
+		// Picks up the value just written above, if this call is also changing it.
+		let hash_iterations: i32 = misc_settings::table
+			.select(misc_settings::password_hash_iterations)
+			.get_result(connection)?;
+
+		for config_user in config_users {
+			let is_new = !existing_names.iter().any(|n| n == &config_user.name);
+
+			if is_new && config_user.password.is_empty() {
+				bail!("Cannot create new user `{}` without a password", config_user.name);
+			}
+
+			if config_user.password.is_empty() {
+				// Empty password on an existing user means "keep the current credentials" so
+				// re-importing an exported config (which never contains real passwords) doesn't
+				// wipe them out. Only the admin flag can still change.
+				diesel::update(users::table.filter(users::name.eq(&config_user.name)))
+					.set(users::admin.eq(config_user.admin))
+					.execute(connection)?;
+				continue;
+			}
+
+			let user = User::new(&config_user.name,
+			                      &config_user.password,
+			                      config_user.admin,
+			                      hash_iterations as u32);
+			if is_new {
+				diesel::insert(&user).into(users::table).execute(connection)?;
+			} else {
+				diesel::update(users::table.filter(users::name.eq(&config_user.name)))
+					.set((users::password_salt.eq(user.password_salt),
+					      users::password_hash.eq(user.password_hash),
+					      users::password_hash_iterations.eq(user.password_hash_iterations),
+					      users::admin.eq(user.admin)))
+					.execute(connection)?;
+			}
+		}
+	}
+
+	if let Some(sleep_duration) = new_config.reindex_every_n_seconds {
+		diesel::update(misc_settings::table)
+			.set(misc_settings::index_sleep_duration_seconds.eq(sleep_duration as i32))
+			.execute(connection)?;
+	}
+
+	if let Some(ref album_art_pattern) = new_config.album_art_pattern {
+		diesel::update(misc_settings::table)
+			.set(misc_settings::index_album_art_pattern.eq(album_art_pattern))
+			.execute(connection)?;
+	}
+
+	if let Some(ref ydns) = new_config.ydns {
+		use self::ddns_config::dsl::*;
+		diesel::update(ddns_config)
+			.set((host.eq(ydns.host.clone()),
+			      username.eq(ydns.username.clone()),
+			      password.eq(ydns.password.clone())))
+			.execute(connection)?;
+	}
+
+	Ok(())
+}
+
+fn clean_path_string(path_string: &str) -> path::PathBuf {
+	let separator_regex = Regex::new(r"\\|/").unwrap();
+	let mut correct_separator = String::new();
+	correct_separator.push(path::MAIN_SEPARATOR);
+	let path_string = separator_regex.replace_all(path_string, correct_separator.as_str());
+	path::Path::new(&path_string).iter().collect()
+}
+
+fn _get_test_db(name: &str) -> DB {
+	let mut db_path = path::PathBuf::new();
+	db_path.push("test");
+	db_path.push(name);
+	if db_path.exists() {
+		fs::remove_file(&db_path).unwrap();
+	}
+
+	let db = DB::new(&db_path).unwrap();
+	db
+}
+
+#[test]
+fn test_ammend() {
+	let db = _get_test_db("ammend.sqlite");
+
+	let initial_config = Config {
+		album_art_pattern: Some("file\\.png".into()),
+		reindex_every_n_seconds: Some(123),
+		password_hash_iterations: Some(1),
+		mount_dirs: Some(vec![MountPoint {
+		                          source: "C:\\Music".into(),
+		                          name: "root".into(),
+		                          backend: vfs::StorageBackendKind::Local,
+		                          remote_url: None,
+		                      }]),
+		users: Some(vec![ConfigUser {
+		                     name: "Teddy🐻".into(),
+		                     password: "tedbear".into(),
+		                     admin: true,
+		                 }]),
+		ydns: Some(DDNSConfig {
+		               host: "🐻🐻🐻.ydns.eu".into(),
+		               username: "be🐻r".into(),
+		               password: "yummy🐇".into(),
+		           }),
+	};
+
+	let final_config = Config {
+		album_art_pattern: Some("🖼️\\.jpg".into()),
+		reindex_every_n_seconds: Some(7734),
+		password_hash_iterations: Some(1),
+		mount_dirs: Some(vec![MountPoint {
+		                          source: "/home/music".into(),
+		                          name: "🎵📁".into(),
+		                          backend: vfs::StorageBackendKind::Local,
+		                          remote_url: None,
+		                      }]),
+		users: Some(vec![ConfigUser {
+		                     name: "Kermit🐸".into(),
+		                     password: "itaintseasy".into(),
+		                     admin: false,
+		                 }]),
+		ydns: Some(DDNSConfig {
+		               host: "🐸🐸🐸.ydns.eu".into(),
+		               username: "kfr🐸g".into(),
+		               password: "tasty🐞".into(),
+		           }),
+	};
+
+	ammend(&db, &initial_config).unwrap();
+	ammend(&db, &final_config).unwrap();
+	let db_config = read(&db).unwrap();
+
+	// Stored passwords are hashed and never round-trip back out, so strip them from the
+	// expectation before comparing.
+	let mut expected_config = final_config;
+	for user in expected_config.users.as_mut().unwrap() {
+		user.password = "".to_owned();
+	}
+	assert_eq!(db_config, expected_config);
+}
+
+#[cfg(test)]
+fn empty_config() -> Config {
+	Config {
+		album_art_pattern: None,
+		reindex_every_n_seconds: None,
+		password_hash_iterations: None,
+		mount_dirs: None,
+		users: None,
+		ydns: None,
+	}
+}
+
+#[test]
+fn test_ammend_rejects_new_user_without_password() {
+	let db = _get_test_db("ammend_rejects_new_user_without_password.sqlite");
+
+	let mut config = empty_config();
+	config.users = Some(vec![ConfigUser {
+	                              name: "Teddy🐻".into(),
+	                              password: "".into(),
+	                              admin: false,
+	                          }]);
+
+	assert!(ammend(&db, &config).is_err());
+}
+
+#[test]
+fn test_ammend_keeps_existing_password_when_empty() {
+	let db = _get_test_db("ammend_keeps_existing_password_when_empty.sqlite");
+
+	let mut config = empty_config();
+	config.users = Some(vec![ConfigUser {
+	                              name: "Teddy🐻".into(),
+	                              password: "tedbear".into(),
+	                              admin: false,
+	                          }]);
+	ammend(&db, &config).unwrap();
+
+	// Re-ammend the same user with an empty password and a flipped admin flag: the existing
+	// hash should survive, but the admin flag should still update.
+	config.users = Some(vec![ConfigUser {
+	                              name: "Teddy🐻".into(),
+	                              password: "".into(),
+	                              admin: true,
+	                          }]);
+	ammend(&db, &config).unwrap();
+
+	let db_config = read(&db).unwrap();
+	let users = db_config.users.unwrap();
+	assert_eq!(users.len(), 1);
+	assert!(users[0].admin);
+
+	let connection = db.get_connection().unwrap();
+	let stored_user: User = users::table
+		.filter(users::name.eq("Teddy🐻"))
+		.get_result(&*connection)
+		.unwrap();
+	assert!(stored_user.verify_password("tedbear"));
+}
+
+#[test]
+fn test_clean_path_string() {
+	let mut correct_path = path::PathBuf::new();
+	if cfg!(target_os = "windows") {
+		correct_path.push("C:\\");
+	} else {
+		correct_path.push("/usr");
+	}
+	correct_path.push("some");
+	correct_path.push("path");
+	if cfg!(target_os = "windows") {
+		assert_eq!(correct_path, clean_path_string(r#"C:/some/path"#));
+		assert_eq!(correct_path, clean_path_string(r#"C:\some\path"#));
+		assert_eq!(correct_path, clean_path_string(r#"C:\some\path\"#));
+		assert_eq!(correct_path, clean_path_string(r#"C:\some\path\\\\"#));
+		assert_eq!(correct_path, clean_path_string(r#"C:\some/path//"#));
+	} else {
+		assert_eq!(correct_path, clean_path_string(r#"/usr/some/path"#));
+		assert_eq!(correct_path, clean_path_string(r#"/usr\some\path"#));
+		assert_eq!(correct_path, clean_path_string(r#"/usr\some\path\"#));
+		assert_eq!(correct_path, clean_path_string(r#"/usr\some\path\\\\"#));
+		assert_eq!(correct_path, clean_path_string(r#"/usr\some/path//"#));
+	}
+}
+
+#[test]
+fn test_json_round_trip() {
+	let config = Config {
+		album_art_pattern: Some("file\\.png".into()),
+		reindex_every_n_seconds: Some(123),
+		password_hash_iterations: None,
+		mount_dirs: Some(vec![MountPoint {
+		                          source: "/home/music".into(),
+		                          name: "root".into(),
+		                          backend: vfs::StorageBackendKind::Local,
+		                          remote_url: None,
+		                      }]),
+		users: Some(vec![ConfigUser {
+		                     name: "Teddy🐻".into(),
+		                     password: "".into(),
+		                     admin: true,
+		                 }]),
+		ydns: None,
+	};
+
+	let json = to_json(&config).unwrap();
+	assert_eq!(config, parse_json(&json).unwrap());
+}
+
+#[test]
+fn test_validate_rejects_bad_album_art_pattern() {
+	let content = r#"{"album_art_pattern": "["}"#;
+	assert!(parse_json(content).is_err());
+}
+
+#[test]
+fn test_validate_rejects_non_positive_hash_iterations() {
+	assert!(parse_json(r#"{"password_hash_iterations": 0}"#).is_err());
+	assert!(parse_json(r#"{"password_hash_iterations": -1}"#).is_err());
+}
+
+#[test]
+fn test_validate_rejects_duplicate_mount_names() {
+	let content = r#"{"mount_dirs": [
+		{"name": "root", "source": "/a"},
+		{"name": "root", "source": "/b"}
+	]}"#;
+	assert!(parse_json(content).is_err());
+}
+
+#[test]
+fn test_validate_rejects_duplicate_mount_sources() {
+	let content = r#"{"mount_dirs": [
+		{"name": "root", "source": "/a"},
+		{"name": "other", "source": "/a"}
+	]}"#;
+	assert!(parse_json(content).is_err());
+}
+
+#[test]
+fn test_validate_rejects_network_mount_without_remote_url() {
+	let content = r#"{"mount_dirs": [
+		{"name": "root", "source": "/a", "backend": "network"}
+	]}"#;
+	assert!(parse_json(content).is_err());
+}
@@ -0,0 +1,121 @@
+use diesel::Connection;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+use db::ConnectionSource;
+use errors::*;
+
+use super::{ammend_connection, parse_toml_file};
+
+// How long to wait for the filesystem to settle down before re-reading the file. Editors tend to
+// save in several steps (truncate, write, rename) that each fire their own event.
+const DEBOUNCE_DURATION_SECONDS: u64 = 2;
+
+// Watches `config_path` for changes and re-applies it to `db` whenever the file is saved. The new
+// config is fully parsed and validated before anything is written to the database, so a broken
+// edit never clobbers the live config; the last-known-good config stays in effect and the error
+// is logged so the operator can fix the file and save again.
+pub fn watch<T>(db: T, config_path: PathBuf) -> Result<()>
+	where T: ConnectionSource + Send + 'static
+{
+	thread::Builder::new()
+		.name("config_watcher".to_owned())
+		.spawn(move || {
+			if let Err(ref e) = run(&db, &config_path) {
+				error!("Config watcher exited unexpectedly: {}", e);
+			}
+		})?;
+	Ok(())
+}
+
+fn run<T>(db: &T, config_path: &Path) -> Result<()>
+	where T: ConnectionSource
+{
+	let config_dir = config_path
+		.parent()
+		.filter(|p| !p.as_os_str().is_empty())
+		.unwrap_or_else(|| Path::new("."));
+	let file_name = config_path
+		.file_name()
+		.ok_or("Config path has no file name")?
+		.to_owned();
+
+	let (tx, rx) = channel();
+	let mut watcher: RecommendedWatcher =
+		Watcher::new(tx, Duration::from_secs(DEBOUNCE_DURATION_SECONDS))?;
+	// Watching the containing directory (rather than `config_path` itself) is what notify's own
+	// docs recommend: editors and config-management tools typically save atomically (write a
+	// temp file, then rename it over the original), and a watch placed directly on a file stops
+	// firing once the inode it was watching gets replaced by the rename.
+	watcher.watch(config_dir, RecursiveMode::NonRecursive)?;
+
+	loop {
+		match rx.recv() {
+			Ok(event) => {
+				if event_path(&event).map(|p| p.file_name()) == Some(Some(file_name.as_os_str())) {
+					reload(db, config_path);
+				}
+			}
+			Err(e) => bail!("Config watcher channel disconnected: {}", e),
+		}
+	}
+}
+
+// Pulls out the path a given event is about, if any. Atomic saves can surface as a `Create` of
+// the final name, a `Write` to it, or a `Rename` into it (temp file -> final name); we only care
+// that the config file's name ended up with new content, not which of those happened.
+fn event_path(event: &DebouncedEvent) -> Option<&Path> {
+	match *event {
+		DebouncedEvent::Create(ref path) |
+		DebouncedEvent::Write(ref path) |
+		DebouncedEvent::Chmod(ref path) => Some(path),
+		DebouncedEvent::Rename(_, ref to) => Some(to),
+		_ => None,
+	}
+}
+
+fn reload<T>(db: &T, config_path: &Path)
+	where T: ConnectionSource
+{
+	match apply(db, config_path) {
+		Ok(()) => info!("Reloaded config from {}", config_path.to_string_lossy()),
+		Err(e) => {
+			error!("Could not reload config from {}: {}. Keeping last known-good config.",
+			       config_path.to_string_lossy(),
+			       e)
+		}
+	}
+}
+
+fn apply<T>(db: &T, config_path: &Path) -> Result<()>
+	where T: ConnectionSource
+{
+	let new_config = parse_toml_file(config_path)?;
+	let connection = db.get_connection()?;
+	connection.transaction(|| ammend_connection(&connection, &new_config))
+}
+
+#[test]
+fn test_event_path_create_write_chmod() {
+	let path = PathBuf::from("/config/polaris.toml");
+	assert_eq!(event_path(&DebouncedEvent::Create(path.clone())), Some(path.as_path()));
+	assert_eq!(event_path(&DebouncedEvent::Write(path.clone())), Some(path.as_path()));
+	assert_eq!(event_path(&DebouncedEvent::Chmod(path.clone())), Some(path.as_path()));
+}
+
+#[test]
+fn test_event_path_rename_uses_destination() {
+	let from = PathBuf::from("/config/polaris.toml.tmp");
+	let to = PathBuf::from("/config/polaris.toml");
+	assert_eq!(event_path(&DebouncedEvent::Rename(from, to.clone())), Some(to.as_path()));
+}
+
+#[test]
+fn test_event_path_ignores_unrelated_events() {
+	assert_eq!(event_path(&DebouncedEvent::Remove(PathBuf::from("/config/polaris.toml"))), None);
+	assert_eq!(event_path(&DebouncedEvent::NoticeWrite(PathBuf::from("/config/polaris.toml"))), None);
+	assert_eq!(event_path(&DebouncedEvent::Rescan), None);
+}
@@ -0,0 +1,94 @@
+use diesel;
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection};
+use diesel::sqlite::SqliteConnection;
+use std::path::Path;
+
+use errors::*;
+
+pub type SqlitePool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
+pub type SqlitePooledConnection = r2d2::PooledConnection<ConnectionManager<SqliteConnection>>;
+
+// Handed out by `ConnectionSource::get_connection`. Callers should drop it as soon as they're
+// done so the connection goes back to the pool for other callers (the background indexer and
+// HTTP request handlers in particular) to use.
+pub trait ConnectionSource {
+	fn get_connection(&self) -> Result<SqlitePooledConnection>;
+}
+
+#[derive(Clone)]
+pub struct DB {
+	pool: SqlitePool,
+}
+
+impl DB {
+	pub fn new(path: &Path) -> Result<DB> {
+		let manager = ConnectionManager::<SqliteConnection>::new(path.to_string_lossy().into_owned());
+		let pool = r2d2::Pool::builder()
+			.connection_customizer(Box::new(SqliteConnectionCustomizer {}))
+			.build(manager)?;
+		let db = DB { pool: pool };
+		db.get_connection()?.execute("")?; // prime the pool so setup errors surface immediately
+		Ok(db)
+	}
+}
+
+impl ConnectionSource for DB {
+	fn get_connection(&self) -> Result<SqlitePooledConnection> {
+		self.pool.get().map_err(|e| e.into())
+	}
+}
+
+// WAL mode lets readers (HTTP requests) proceed while a writer (the indexer, a config reload) is
+// mid-transaction, and the busy timeout makes the rare write/write conflict block briefly instead
+// of failing outright, so the pool doesn't deadlock or error out under concurrent access.
+#[derive(Debug)]
+struct SqliteConnectionCustomizer;
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for SqliteConnectionCustomizer {
+	fn on_acquire(&self, connection: &mut SqliteConnection) -> ::std::result::Result<(), r2d2::Error> {
+		connection
+			.execute("PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000;")
+			.map(|_| ())
+			.map_err(r2d2::Error::QueryError)
+	}
+}
+
+table! {
+	ddns_config (id) {
+		id -> Integer,
+		host -> Text,
+		username -> Text,
+		password -> Text,
+	}
+}
+
+table! {
+	misc_settings (id) {
+		id -> Integer,
+		auth_secret -> Text,
+		index_sleep_duration_seconds -> Integer,
+		index_album_art_pattern -> Text,
+		password_hash_iterations -> Integer,
+	}
+}
+
+table! {
+	mount_points (source) {
+		source -> Text,
+		name -> Text,
+		backend -> Text,
+		remote_url -> Nullable<Text>,
+	}
+}
+
+table! {
+	users (name) {
+		name -> Text,
+		password_salt -> Binary,
+		password_hash -> Binary,
+		// Stored per-user (rather than read from the current `misc_settings` value) so that
+		// raising `password_hash_iterations` later doesn't invalidate already-hashed passwords.
+		password_hash_iterations -> Integer,
+		admin -> Bool,
+	}
+}
@@ -0,0 +1,82 @@
+use rand::{self, RngCore};
+use ring::pbkdf2;
+use std::num::NonZeroU32;
+
+use db::users;
+
+static PBKDF2_ALG: &'static pbkdf2::Algorithm = &pbkdf2::PBKDF2_HMAC_SHA256;
+const PBKDF2_SALT_LEN: usize = 16;
+const PBKDF2_HASH_LEN: usize = 32;
+
+// A reference iteration count, used by the tests below. New hashes written by `config::ammend` use
+// the `password_hash_iterations` value stored in `misc_settings` instead, not this constant.
+pub const DEFAULT_PBKDF2_ITERATIONS: u32 = 10240;
+
+#[derive(Debug, Insertable, Queryable)]
+#[table_name = "users"]
+pub struct User {
+	pub name: String,
+	pub password_salt: Vec<u8>,
+	pub password_hash: Vec<u8>,
+	pub password_hash_iterations: i32,
+	pub admin: bool,
+}
+
+impl User {
+	// `hash_iterations` is stamped onto the user at hash time (not re-read from config when
+	// verifying), so raising the configured iteration count later doesn't break logins for users
+	// hashed under the old count.
+	pub fn new(name: &str, password: &str, admin: bool, hash_iterations: u32) -> User {
+		let salt = generate_salt();
+		let hash = hash_password(&salt, password, hash_iterations);
+		User {
+			name: name.to_owned(),
+			password_salt: salt,
+			password_hash: hash,
+			password_hash_iterations: hash_iterations as i32,
+			admin: admin,
+		}
+	}
+
+	pub fn verify_password(&self, password: &str) -> bool {
+		let iterations = match NonZeroU32::new(self.password_hash_iterations as u32) {
+			Some(iterations) => iterations,
+			None => return false,
+		};
+		pbkdf2::verify(*PBKDF2_ALG,
+		               iterations,
+		               &self.password_salt,
+		               password.as_bytes(),
+		               &self.password_hash)
+			.is_ok()
+	}
+}
+
+fn generate_salt() -> Vec<u8> {
+	let mut salt = vec![0u8; PBKDF2_SALT_LEN];
+	rand::thread_rng().fill_bytes(&mut salt);
+	salt
+}
+
+fn hash_password(salt: &[u8], password: &str, hash_iterations: u32) -> Vec<u8> {
+	let mut hash = vec![0u8; PBKDF2_HASH_LEN];
+	pbkdf2::derive(*PBKDF2_ALG,
+	               NonZeroU32::new(hash_iterations).unwrap(),
+	               salt,
+	               password.as_bytes(),
+	               &mut hash);
+	hash
+}
+
+#[test]
+fn test_verify_password() {
+	let user = User::new("Teddy🐻", "tedbear", false, DEFAULT_PBKDF2_ITERATIONS);
+	assert!(user.verify_password("tedbear"));
+	assert!(!user.verify_password("wrong password"));
+}
+
+#[test]
+fn test_verify_password_respects_stored_iteration_count() {
+	let user = User::new("Teddy🐻", "tedbear", false, 1);
+	assert!(user.verify_password("tedbear"));
+}